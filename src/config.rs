@@ -0,0 +1,153 @@
+// The socket address used to live as the hardcoded tuple `([127, 0, 0, 1], 8080)` in
+// `main`. Normally, a `SocketAddr` should be created from external strings, like
+// command-line arguments or environment variables, so this module resolves one from
+// `--address`/`-a` on the CLI, then the `MICROSERVICE_ADDR` environment variable, falling
+// back to the old default if neither is set.
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use hyper::Uri;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+const ADDR_ENV_VAR: &str = "MICROSERVICE_ADDR";
+const STORE_ENV_VAR: &str = "MICROSERVICE_STORE";
+const LMDB_PATH_ENV_VAR: &str = "MICROSERVICE_LMDB_PATH";
+const DEFAULT_LMDB_PATH: &str = "./microservice-data";
+const PROXY_ENV_VAR: &str = "MICROSERVICE_PROXY";
+
+// Resolves the address the server should bind to, in that order of precedence. Since
+// `SocketAddr`'s `FromStr` impl already understands both IPv4 and IPv6 (`[::1]:8080`),
+// this gets IPv6 support for free. Prints a message and exits the process if the chosen
+// string doesn't parse, rather than failing deep inside `Server::bind`.
+pub fn resolve_addr() -> SocketAddr {
+    let raw = address_from_args()
+        .or_else(address_from_env)
+        .unwrap_or_else(|| DEFAULT_ADDR.to_owned());
+
+    raw.parse().unwrap_or_else(|e| {
+        eprintln!("invalid address '{}': {}", raw, e);
+        std::process::exit(1);
+    })
+}
+
+// Looks for `--address <addr>`, `--address=<addr>`, or the short form `-a <addr>` among
+// the process's command-line arguments.
+fn address_from_args() -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    for (i, arg) in args.iter().enumerate() {
+        if let Some(stripped) = arg.strip_prefix("--address=") {
+            return Some(stripped.to_owned());
+        }
+        if (arg == "--address" || arg == "-a") && i + 1 < args.len() {
+            return Some(args[i + 1].clone());
+        }
+    }
+    None
+}
+
+fn address_from_env() -> Option<String> {
+    env::var(ADDR_ENV_VAR).ok()
+}
+
+// Which `UserStore` implementation `main` should construct.
+pub enum StoreBackend {
+    // The default: data is gone once the process exits.
+    Memory,
+    // Durable storage at the given directory, backed by LMDB.
+    Lmdb(PathBuf),
+}
+
+// Selects the storage backend from `MICROSERVICE_STORE` (`"memory"`, the default, or
+// `"lmdb"`), with the LMDB data directory coming from `MICROSERVICE_LMDB_PATH` (falling
+// back to `./microservice-data`).
+pub fn resolve_store_backend() -> StoreBackend {
+    match env::var(STORE_ENV_VAR).unwrap_or_default().as_str() {
+        "lmdb" => {
+            let path = env::var(LMDB_PATH_ENV_VAR).unwrap_or_else(|_| DEFAULT_LMDB_PATH.to_owned());
+            StoreBackend::Lmdb(PathBuf::from(path))
+        }
+        _ => StoreBackend::Memory,
+    }
+}
+
+// Parses `MICROSERVICE_PROXY` as a comma-separated list of `prefix=upstream` pairs, e.g.
+// `MICROSERVICE_PROXY=/legacy=http://localhost:9090,/old=http://localhost:9091`. Unset (the
+// default) means no path is ever proxied. Entries that don't contain an `=`, or whose
+// upstream isn't a parseable URI, are skipped with a warning rather than carried forward
+// to fail on the very first request that hits them.
+pub fn resolve_proxy_routes() -> Vec<(String, String)> {
+    let raw = match env::var(PROXY_ENV_VAR) {
+        Ok(raw) => raw,
+        Err(_) => return Vec::new(),
+    };
+
+    parse_proxy_routes(&raw)
+}
+
+// The actual parsing, split out from `resolve_proxy_routes` so it can be unit-tested
+// against a literal string instead of the process environment.
+fn parse_proxy_routes(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                return None;
+            }
+            let mut parts = entry.splitn(2, '=');
+            let prefix = parts.next()?.trim();
+            let upstream = match parts.next() {
+                Some(upstream) => upstream.trim(),
+                None => {
+                    eprintln!(
+                        "{}: skipping entry '{}', it has no '=' between prefix and upstream",
+                        PROXY_ENV_VAR, entry
+                    );
+                    return None;
+                }
+            };
+            if prefix.is_empty() || upstream.is_empty() {
+                return None;
+            }
+            if upstream.parse::<Uri>().is_err() {
+                eprintln!(
+                    "{}: skipping route for prefix '{}', upstream '{}' isn't a valid URI",
+                    PROXY_ENV_VAR, prefix, upstream
+                );
+                return None;
+            }
+            Some((prefix.to_owned(), upstream.to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_multiple_prefix_upstream_pairs() {
+        let routes = parse_proxy_routes("/legacy=http://localhost:9090,/old=http://localhost:9091");
+        assert_eq!(
+            routes,
+            vec![
+                ("/legacy".to_owned(), "http://localhost:9090".to_owned()),
+                ("/old".to_owned(), "http://localhost:9091".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_entries_missing_the_delimiter_or_with_an_unparseable_upstream() {
+        let routes = parse_proxy_routes("no-delimiter-here,/bad=not a uri,/good=http://localhost:9090");
+        assert_eq!(
+            routes,
+            vec![("/good".to_owned(), "http://localhost:9090".to_owned())]
+        );
+    }
+
+    #[test]
+    fn empty_input_yields_no_routes() {
+        assert!(parse_proxy_routes("").is_empty());
+    }
+}
@@ -1,25 +1,39 @@
 // CLI (hot reload) -> cargo watch -x "run"
-use futures::{future, Future};
+mod config;
+mod proxy;
+mod router;
+mod rpc;
+mod store;
+
+use futures::future::Either;
+use futures::{Future, Stream};
 use hyper::service::service_fn;
 use hyper::{Body, Error, Method, Request, Response, Server, StatusCode};
 use lazy_static::lazy_static;
-use regex::Regex;
-use slab::Slab;
-use std::fmt;
-use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use router::Router;
+use store::{LmdbUserStore, MemoryUserStore, UserStore};
 
 fn main() {
-    // Socket address that consists of an IP address and a port number (IPv4).
-    // Using the SocketAddr struct, which contains both the IpAddr and the u16 from the tuple ([u8; 4], 16).
-    // RUST TRAIT -> impl<I: Into<IpAddr>> From<(I, u16)> for SocketAddr --> impl From<[u8; 4]> for IpAddr
-    // .into() method call to construct a socket address from the tuple
-    let addr = ([127, 0, 0, 1], 8080).into();
+    // Socket address that consists of an IP address and a port number. Resolved from
+    // `--address`/`MICROSERVICE_ADDR` with a fallback to the old hardcoded default, so
+    // deployments can bind somewhere other than localhost:8080 without a rebuild.
+    let addr = config::resolve_addr();
 
     // We create a server instance and bind it to this address, it actually returns Builder, not a Server instance.
     let builder = Server::bind(&addr);
 
-    // We also have to send the reference (of this shared state) to the main func
-    let user_db = Arc::new(Mutex::new(Slab::new()));
+    // We also have to send the reference (of this shared state) to the main func. Which
+    // `UserStore` backs it is chosen by `MICROSERVICE_STORE`: the default in-memory one,
+    // or an LMDB-backed one that survives restarts.
+    let user_db: UserDb = match config::resolve_store_backend() {
+        config::StoreBackend::Memory => Arc::new(MemoryUserStore::new()),
+        config::StoreBackend::Lmdb(path) => {
+            Arc::new(LmdbUserStore::open(&path).expect("failed to open LMDB store"))
+        }
+    };
 
     // The Builder struct provides methods to tweak the parameters of the server created
     // We use builder to attach a service for handling incoming HTTP requests using the serve method
@@ -74,149 +88,149 @@ fn microservice_handler(
     req: Request<Body>,
     user_db: &UserDb,
 ) -> impl Future<Item = Response<Body>, Error = Error> {
-    let response = {
-        let method = req.method();
-        let path = req.uri().path();
-        let mut users = user_db.lock().unwrap();
+    // Proxied paths are forwarded before we touch the body at all: the upstream gets the
+    // original, unconsumed request stream rather than a re-serialized copy of it.
+    if let Some(upstream) = proxy::upstream_for(req.uri().path()) {
+        return Either::A(proxy::forward(req, upstream));
+    }
 
-        if INDEX_PATH.is_match(path) {
-            if method == &Method::GET {
-                Response::new(INDEX.into())
-            } else {
-                response_with_code(StatusCode::METHOD_NOT_ALLOWED)
-            }
-        } else if USERS_PATH.is_match(path) {
-            if method == &Method::GET {
-                let list = users
-                    .iter()
-                    .map(|(id, _)| id.to_string())
-                    .collect::<Vec<String>>()
-                    .join(",");
-                Response::new(list.into())
-            } else {
-                response_with_code(StatusCode::METHOD_NOT_ALLOWED)
-            }
-        } else if let Some(cap) = USER_PATH.captures(path) {
-            let user_id = cap
-                .name("user_id")
-                .and_then(|m| m.as_str().parse::<UserId>().ok().map(|x| x as usize));
-            match (method, user_id) {
-                (&Method::GET, Some(id)) => {
-                    if let Some(data) = users.get(id) {
-                        Response::new(Body::from(data.to_string()))
-                    } else {
-                        response_with_code(StatusCode::NOT_FOUND)
-                    }
-                }
+    let user_db = Arc::clone(user_db);
+    let method = req.method().clone();
+    let path = req.uri().path().to_owned();
 
-                (&Method::PUT, Some(id)) => {
-                    if let Some(user) = users.get_mut(id) {
-                        *user = UserData;
-                        response_with_code(StatusCode::OK)
-                    } else {
-                        response_with_code(StatusCode::NOT_FOUND)
-                    }
-                }
+    // The body arrives as a stream of chunks, so we concatenate it into a single buffer
+    // before we can hand it to serde_json. Only POST/PUT actually carry a body, but it's
+    // simplest to always wait for it and just ignore it for the other methods.
+    Either::B(
+        req.into_body()
+            .concat2()
+            .map(move |chunks| ROUTER.handle(&method, &path, &chunks, &user_db)),
+    )
+}
 
-                (&Method::POST, None) => {
-                    let id = users.insert(UserData);
-                    Response::new(Body::from(id.to_string()))
-                }
+fn user_id_from_captures(caps: &regex::Captures) -> Option<UserId> {
+    caps.name("user_id").and_then(|m| m.as_str().parse::<UserId>().ok())
+}
 
-                (&Method::POST, Some(_)) => response_with_code(StatusCode::BAD_REQUEST),
+// Deserializes a client-supplied `UserData` and runs `UserData::validate` on it, so a
+// malformed body and a structurally valid but semantically empty one both come back as a
+// single error a handler can turn into `400 Bad Request`.
+fn parse_and_validate_user(body: &hyper::Chunk) -> Result<UserData, ()> {
+    let user: UserData = serde_json::from_slice(body).map_err(|_| ())?;
+    user.validate().map_err(|_| ())?;
+    Ok(user)
+}
 
-                (&Method::DELETE, Some(id)) => {
-                    if users.contains(id) {
-                        users.remove(id);
+// The current time as a Unix timestamp (seconds). Used to stamp `UserData::created_at`
+// at the moment a record is created, since that's server state the client doesn't get a
+// say in.
+pub(crate) fn now_unix() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+lazy_static! {
+    // Built once at startup: the same ordered dispatch the old `if`/`else if` ladder did,
+    // just expressed as a table instead of nested conditionals. `/user/...` is registered
+    // once per method (all four share the same pattern) so that, e.g., a `POST` with an id
+    // still reaches the handler and can report `400` rather than falling through to `405`.
+    static ref ROUTER: Router = Router::new()
+        .route(Method::GET, r"^/(index\.html?)?$", Box::new(|_body, _caps, _db| {
+            Response::new(INDEX.into())
+        }))
+        .route(Method::GET, r"^/users/?$", Box::new(|_body, _caps, db| {
+            let list = db
+                .list()
+                .into_iter()
+                .map(|(id, user)| UserWithId::new(id, user))
+                .collect::<Vec<_>>();
+            json_response(&list)
+        }))
+        .route(Method::GET, USER_PATH_PATTERN, Box::new(|_body, caps, db| {
+            match user_id_from_captures(caps) {
+                Some(id) => match db.get(id) {
+                    Some(user) => json_response(&UserWithId::new(id, user)),
+                    None => response_with_code(StatusCode::NOT_FOUND),
+                },
+                None => response_with_code(StatusCode::METHOD_NOT_ALLOWED),
+            }
+        }))
+        .route(Method::PUT, USER_PATH_PATTERN, Box::new(|body, caps, db| {
+            match user_id_from_captures(caps) {
+                Some(id) => match parse_and_validate_user(body) {
+                    Ok(mut user) => {
+                        // An update keeps the creation time the record was stamped with.
+                        user.created_at = match db.get(id) {
+                            Some(existing) => existing.created_at,
+                            None => return response_with_code(StatusCode::NOT_FOUND),
+                        };
+                        match db.update(id, user) {
+                            Ok(true) => response_with_code(StatusCode::OK),
+                            Ok(false) => response_with_code(StatusCode::NOT_FOUND),
+                            Err(_) => response_with_code(StatusCode::INTERNAL_SERVER_ERROR),
+                        }
+                    }
+                    Err(_) => response_with_code(StatusCode::BAD_REQUEST),
+                },
+                None => response_with_code(StatusCode::METHOD_NOT_ALLOWED),
+            }
+        }))
+        .route(Method::POST, USER_PATH_PATTERN, Box::new(|body, caps, db| {
+            match user_id_from_captures(caps) {
+                Some(_) => response_with_code(StatusCode::BAD_REQUEST),
+                None => match parse_and_validate_user(body) {
+                    Ok(mut user) => {
+                        user.created_at = now_unix();
+                        let user_for_response = user.clone();
+                        match db.insert(user) {
+                            Ok(id) => json_response(&UserWithId::new(id, user_for_response)),
+                            Err(_) => response_with_code(StatusCode::INTERNAL_SERVER_ERROR),
+                        }
+                    }
+                    Err(_) => response_with_code(StatusCode::BAD_REQUEST),
+                },
+            }
+        }))
+        .route(Method::DELETE, USER_PATH_PATTERN, Box::new(|_body, caps, db| {
+            match user_id_from_captures(caps) {
+                Some(id) => {
+                    if db.remove(id) {
                         response_with_code(StatusCode::OK)
                     } else {
                         response_with_code(StatusCode::NOT_FOUND)
                     }
                 }
-                _ => response_with_code(StatusCode::METHOD_NOT_ALLOWED),
+                None => response_with_code(StatusCode::METHOD_NOT_ALLOWED),
             }
-        } else {
-            response_with_code(StatusCode::NOT_FOUND)
-        }
-
-        // match (req.method(), req.uri().path()) {
-        //     (&Method::GET, "/") => Response::new(Body::from(INDEX)),
-        //     // we use an if expression to detect that the path starts with '/user/' prefix
-        //     (method, path) if path.starts_with(USER_PATH) => {
-        //         // the str::trim_left_matches method removes the part of the string if it matches a provided string from the arg
-        //         // we use the str::parse method, which tries to convert a string (the remaining tail) to a type that implements the FromStr trait of the standard library.
-        //         // UserId already implements this, because it's equal to the u64 type, which can be parsed from the string.
-        //         // The parse method returns Result. We convert this to an Option instance with Result::ok functions.
-        //         let user_id = path
-        //             .trim_start_matches(USER_PATH)
-        //             .parse::<UserId>()
-        //             .ok()
-        //             .map(|x| x as usize);
-        //         let mut users = user_db.lock().unwrap();
-
-        // match (method, user_id) {
-        //     // When the data is created, we need to be able to read it.
-        //     (&Method::GET, Some(id)) => {
-        //         if let Some(data) = users.get(id) {
-        //             Response::new(data.to_string().into())
-        //         } else {
-        //             response_with_code(StatusCode::NOT_FOUND)
-        //         }
-        //     }
-
-        //     // Once the data is saved, we might want to provide the ability to modify it.
-        //     (&Method::PUT, Some(id)) => {
-        //         // Code tries to find a user instance in the user database with the get_mut method.
-        //         // This returns a mutable reference wrapped with either a Some option, or a None option.
-        //         // We can use a dereference operator, *, to replace the data in the storage.
-        //         if let Some(user) = users.get_mut(id) {
-        //             *user = UserData;
-        //             response_with_code(StatusCode::OK)
-        //         } else {
-        //             response_with_code(StatusCode::NOT_FOUND)
-        //         }
-        //     }
-
-        //     // When the server has just started, it doesn't contain any data. To support data creation, we use the POST method without the user's ID.
-        //     // This code adds a UserData instance to the user database and sends the associated ID of the user in a response with the OK status (an HTTP status code of 200).
-        //     (&Method::POST, None) => {
-        //         let id = users.insert(UserData);
-        //         Response::new(Body::from(id.to_string()))
-        //     }
-        //     // What if the client sets the ID with a POST request? We'll inform the client that the request was wrong.
-        //     (&Method::POST, Some(_)) => response_with_code(StatusCode::BAD_REQUEST),
-
-        //     // When we don't need data anymore, we can delete it.
-        //     (&Method::DELETE, Some(id)) => {
-        //         if users.contains(id) {
-        //             users.remove(id);
-        //             response_with_code(StatusCode::OK)
-        //         } else {
-        //             response_with_code(StatusCode::NOT_FOUND)
-        //         }
-        //     }
-
-        //     _ => response_with_code(StatusCode::METHOD_NOT_ALLOWED),
-        // }
-        //     }
-        //     _ => response_with_code(StatusCode::NOT_FOUND),
-        // }
-    };
-    future::ok(response)
+        }))
+        .route(Method::POST, r"^/rpc/?$", Box::new(|body, _caps, db| {
+            rpc::handle(body, db)
+        }));
 }
 
-lazy_static! {
-    // index.htm | index.html | /
-    static ref INDEX_PATH: Regex = Regex::new("^/(index\\.html?)?$").unwrap();
-    // /user/ | /user/<id> | /user/<id>/
-    static ref USER_PATH: Regex = Regex::new("^/user/((?P<user_id>\\d+?)/?)?$").unwrap();
-    // /users/ | /users
-    static ref USERS_PATH: Regex = Regex::new("^/users/?$").unwrap();
+// Builds a `200 OK` response whose body is the JSON encoding of `value`, with the
+// `Content-Type` header set accordingly. Falls back to `500` if serialization fails,
+// which should only happen if `UserData` stops being representable as JSON.
+pub(crate) fn json_response<T: Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_string(value) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(_) => response_with_code(StatusCode::INTERNAL_SERVER_ERROR),
+    }
 }
-//const USER_PATH: &str = "/user/";
+
+// /user/ | /user/<id> | /user/<id>/ — shared by every `/user/...` route, since the
+// handlers (not the router) are what tells a missing id apart from a present one.
+const USER_PATH_PATTERN: &str = r"^/user/((?P<user_id>\d+?)/?)?$";
 
 // HTML code. r#...# is for multiline string blobs
-const INDEX: &'static str = r#"
+const INDEX: &str = r#"
  <!doctype html>
  <html>
      <head>
@@ -229,32 +243,77 @@ const INDEX: &'static str = r#"
  "#;
 
 // Some types to handle a user database, which will hold data about users
-type UserId = u64;
-struct UserData;
+pub(crate) type UserId = u64;
+
+// The actual fields we keep for a user. `created_at` is a Unix timestamp (seconds) rather
+// than a richer date type so the struct stays trivially (de)serializable without pulling
+// in a date/time crate just for this. It's `#[serde(default)]` because it's the server,
+// not the client, that owns when a record was created: a creating request stamps it with
+// `now_unix()` right before the insert, so any `created_at` the client sends is discarded
+// rather than letting callers backdate records. `Clone` lets a `UserStore` hand back an
+// owned copy of whatever it has stored without exposing how it stores it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct UserData {
+    name: String,
+    email: String,
+    #[serde(default)]
+    created_at: u64,
+}
+
+#[cfg(test)]
+impl UserData {
+    pub(crate) fn new(name: impl Into<String>, email: impl Into<String>, created_at: u64) -> Self {
+        Self {
+            name: name.into(),
+            email: email.into(),
+            created_at,
+        }
+    }
+}
+
 // Arc is an atomic reference counter that provides multiple references to a single instance of data.
 // Atomic entities can be safely used with multiple threads.
-// Mutex is a mutual-exclusion wrapper that controls access to mutable data. Mutex is an atomic flag that
-// checks that only one thread has access to the data, and other threads have to wait until the thread that
-// has locked the mutex releases it.
-// Slab is an allocator that can store and remove any value identified by an ordered number
-// In this case, we use Slab to allocate new IDs for users and to keep the data with the user.
-// We use Arc with the Mutex pair to protect our database of data race, because different responses can be processed in different threads, which can both try to access the database.
-type UserDb = Arc<Mutex<Slab<UserData>>>;
+// Request handling talks to whichever `UserStore` was constructed in `main` purely through
+// the trait, so the same routes work whether it's backed by the in-memory `Slab` or the
+// durable LMDB store.
+pub(crate) type UserDb = Arc<dyn UserStore>;
+
+// GET /users returns the id alongside each user's data, since a `UserStore` doesn't keep
+// the id inside `UserData` itself. This wrapper flattens the two into a single JSON object.
+#[derive(Debug, Serialize)]
+pub(crate) struct UserWithId {
+    id: UserId,
+    #[serde(flatten)]
+    user: UserData,
+}
+
+impl UserWithId {
+    pub(crate) fn new(id: UserId, user: UserData) -> Self {
+        Self { id, user }
+    }
+}
 
 // We need a helper function that creates empty responses with the corresponding HTTP status codes
 // This func expects a status code, creates a new response builder, sets the status and adds an empty body
-fn response_with_code(status_code: StatusCode) -> Response<Body> {
+pub(crate) fn response_with_code(status_code: StatusCode) -> Response<Body> {
     Response::builder()
         .status(status_code)
         .body(Body::empty())
         .unwrap()
 }
 
-// To make the UserData convertible to a String, we have to implement the ToString trait for that type.
-// However, it's typically more useful to implement the Display trait
-// In this implementation, we return a string with an empty JSON object "{}".
-impl fmt::Display for UserData {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str("{}")
+impl UserData {
+    // Beyond being structurally valid JSON, a `name` and an `@`-containing `email` are the
+    // only things this service can meaningfully check without an external verification
+    // step (e.g. actually emailing the address). Called by every handler that accepts a
+    // client-supplied `UserData` before it's stored.
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty");
+        }
+        if !self.email.contains('@') {
+            return Err("email must be a valid email address");
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,63 @@
+// Optional reverse-proxy mode: requests whose path starts with one of these prefixes are
+// forwarded to an upstream service instead of being served from the local `UserDb`. This
+// lets the microservice sit in front of another service without every caller needing to
+// know where that other service actually lives.
+use futures::future::{self, Either};
+use futures::Future;
+use hyper::client::HttpConnector;
+use hyper::header::HeaderValue;
+use hyper::{Body, Client, Error, Request, Response, StatusCode, Uri};
+use lazy_static::lazy_static;
+
+use crate::{config, response_with_code};
+
+lazy_static! {
+    static ref CLIENT: Client<HttpConnector> = Client::new();
+    // Each entry maps a path prefix on this service onto the base URI of the upstream it
+    // should be forwarded to, checked in order. Configured through `MICROSERVICE_PROXY`;
+    // empty (the default) means no path is ever proxied.
+    static ref PROXY_ROUTES: Vec<(String, String)> = config::resolve_proxy_routes();
+}
+
+// Returns the upstream base URI to forward to, if `path` starts with one of the
+// configured prefixes.
+pub fn upstream_for(path: &str) -> Option<&'static str> {
+    PROXY_ROUTES
+        .iter()
+        .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+        .map(|(_, upstream)| upstream.as_str())
+}
+
+// Rewrites `req`'s URI (and `Host` header, since that's what most upstreams actually
+// route on) to point at `upstream`, keeping the original path and query, and otherwise
+// forwards it unchanged: method, the rest of the headers, and the body all pass through
+// as-is. The upstream's response is streamed straight back to our caller.
+pub fn forward(req: Request<Body>, upstream: &str) -> impl Future<Item = Response<Body>, Error = Error> {
+    let (mut parts, body) = req.into_parts();
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    // `upstream` was already validated as a parseable URI when the route was configured
+    // (see `config::resolve_proxy_routes`), but combining it with the request's own path
+    // and query is a fresh parse every request, so a surprising combination still gets a
+    // clean `502` instead of panicking this handler.
+    let uri: Uri = match format!("{}{}", upstream.trim_end_matches('/'), path_and_query).parse() {
+        Ok(uri) => uri,
+        Err(_) => return Either::A(future::ok(response_with_code(StatusCode::BAD_GATEWAY))),
+    };
+
+    if let Some(host) = uri.host() {
+        let host_header = match uri.port_part() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&host_header) {
+            parts.headers.insert(hyper::header::HOST, value);
+        }
+    }
+    parts.uri = uri;
+
+    Either::B(CLIENT.request(Request::from_parts(parts, body)))
+}
@@ -0,0 +1,104 @@
+// A small routing table that replaces the hand-written `if`/`else if` regex ladder that
+// used to live in `microservice_handler`. Routes are registered in order and matched in
+// that same order, so more specific patterns should be added before more general ones.
+use hyper::{Body, Chunk, Method, Response, StatusCode};
+use regex::{Captures, Regex};
+
+use crate::{response_with_code, UserDb};
+
+// A handler receives the already-concatenated request body, the named regex captures
+// pulled from the path, and a reference to the shared user database, and produces the
+// response synchronously. Boxed so the `Router` can hold a heterogeneous list of them.
+pub type Handler = Box<dyn Fn(&Chunk, &Captures, &UserDb) -> Response<Body> + Send + Sync>;
+
+struct Route {
+    method: Method,
+    pattern: Regex,
+    handler: Handler,
+}
+
+// Holds the ordered list of `(Method, Regex, Handler)` entries and matches incoming
+// requests against them. Built once at startup via the `Router::new().route(...)...`
+// builder, then shared (through a `lazy_static`) across every request.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router { routes: Vec::new() }
+    }
+
+    // Compiles `pattern` once and registers `handler` to run for `method` requests whose
+    // path matches it. Panics on an invalid pattern, since routes are only ever built from
+    // string literals at startup.
+    pub fn route(mut self, method: Method, pattern: &str, handler: Handler) -> Self {
+        self.routes.push(Route {
+            method,
+            pattern: Regex::new(pattern).expect("invalid route pattern"),
+            handler,
+        });
+        self
+    }
+
+    // Matches `method`/`path` against the registered routes in order and invokes the
+    // first handler whose pattern and method both match. If the path matches some route
+    // but none of them accept `method`, responds with `405`; if no route's pattern
+    // matches the path at all, responds with `404`.
+    pub fn handle(&self, method: &Method, path: &str, body: &Chunk, user_db: &UserDb) -> Response<Body> {
+        let mut path_matched = false;
+        for route in &self.routes {
+            if let Some(caps) = route.pattern.captures(path) {
+                path_matched = true;
+                if route.method == *method {
+                    return (route.handler)(body, &caps, user_db);
+                }
+            }
+        }
+        if path_matched {
+            response_with_code(StatusCode::METHOD_NOT_ALLOWED)
+        } else {
+            response_with_code(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryUserStore;
+    use std::sync::Arc;
+
+    fn test_db() -> UserDb {
+        Arc::new(MemoryUserStore::new())
+    }
+
+    fn ok_handler() -> Handler {
+        Box::new(|_body, _caps, _db| Response::new(Body::empty()))
+    }
+
+    #[test]
+    fn path_with_no_matching_route_is_404() {
+        let router = Router::new().route(Method::GET, r"^/known$", ok_handler());
+        let db = test_db();
+        let response = router.handle(&Method::GET, "/unknown", &Chunk::from(""), &db);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn path_matches_but_method_does_not_is_405() {
+        let router = Router::new().route(Method::GET, r"^/known$", ok_handler());
+        let db = test_db();
+        let response = router.handle(&Method::POST, "/known", &Chunk::from(""), &db);
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[test]
+    fn path_and_method_match_invokes_the_handler() {
+        let router = Router::new().route(Method::GET, r"^/known$", ok_handler());
+        let db = test_db();
+        let response = router.handle(&Method::GET, "/known", &Chunk::from(""), &db);
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
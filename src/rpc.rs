@@ -0,0 +1,280 @@
+// A JSON-RPC 2.0 endpoint that sits alongside the REST routes and talks to the same
+// `UserDb`, so clients that prefer a single RPC surface over individual REST paths can use
+// `user.create`/`user.get`/`user.list`/`user.delete` instead. See
+// https://www.jsonrpc.org/specification for the envelope and error-code conventions this
+// follows.
+use hyper::{Body, Chunk, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{json_response, response_with_code, UserData, UserDb, UserId, UserWithId};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+// Implementation-defined server error, in the range the spec reserves for that purpose.
+const USER_NOT_FOUND: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+// The router-facing entry point: parses the request body as either a single JSON-RPC
+// envelope or a batch (a JSON array of them), dispatches each to `call_method`, and
+// assembles the matching response. A body that isn't valid JSON at all gets a single
+// top-level parse-error response, per the spec.
+pub fn handle(body: &Chunk, user_db: &UserDb) -> Response<Body> {
+    let value: Value = match serde_json::from_slice(body) {
+        Ok(value) => value,
+        Err(_) => return json_response(&error_response(Value::Null, PARSE_ERROR, "Parse error")),
+    };
+
+    match value {
+        Value::Array(items) => {
+            if items.is_empty() {
+                return json_response(&error_response(
+                    Value::Null,
+                    INVALID_REQUEST,
+                    "Invalid Request",
+                ));
+            }
+            let responses: Vec<RpcResponse> = items
+                .into_iter()
+                .filter_map(|item| dispatch_one(item, user_db))
+                .collect();
+            if responses.is_empty() {
+                response_with_code(StatusCode::NO_CONTENT)
+            } else {
+                json_response(&responses)
+            }
+        }
+        single => match dispatch_one(single, user_db) {
+            Some(response) => json_response(&response),
+            None => response_with_code(StatusCode::NO_CONTENT),
+        },
+    }
+}
+
+// Handles one call out of a request or batch. Returns `None` for a notification (no `id`
+// in the original envelope), since the spec says the server must not reply to those.
+fn dispatch_one(value: Value, user_db: &UserDb) -> Option<RpcResponse> {
+    let id = value.get("id").cloned();
+
+    let request: RpcRequest = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(_) => {
+            return Some(error_response(
+                id.unwrap_or(Value::Null),
+                INVALID_REQUEST,
+                "Invalid Request",
+            ))
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        return Some(error_response(
+            id.unwrap_or(Value::Null),
+            INVALID_REQUEST,
+            "Invalid Request",
+        ));
+    }
+
+    let result = call_method(&request.method, request.params, user_db);
+    let id = id?;
+
+    Some(match result {
+        Ok(value) => RpcResponse {
+            jsonrpc: "2.0",
+            result: Some(value),
+            error: None,
+            id,
+        },
+        Err((code, message)) => RpcResponse {
+            jsonrpc: "2.0",
+            result: None,
+            error: Some(RpcError { code, message }),
+            id,
+        },
+    })
+}
+
+type MethodResult = Result<Value, (i64, String)>;
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: UserId,
+}
+
+// Maps a JSON-RPC method name onto the same CRUD operations the REST `/user(s)` routes
+// expose, reusing `UserData`/`UserWithId` so both surfaces serialize users identically.
+fn call_method(method: &str, params: Value, user_db: &UserDb) -> MethodResult {
+    match method {
+        "user.create" => {
+            let mut user: UserData = serde_json::from_value(params)
+                .map_err(|_| (INVALID_PARAMS, "Invalid params".to_owned()))?;
+            user.validate()
+                .map_err(|e| (INVALID_PARAMS, e.to_owned()))?;
+            user.created_at = crate::now_unix();
+            let user_for_response = user.clone();
+            let id = user_db
+                .insert(user)
+                .map_err(|_| (INTERNAL_ERROR, "Internal error".to_owned()))?;
+            to_value(&UserWithId::new(id, user_for_response))
+        }
+        "user.get" => {
+            let IdParams { id } = serde_json::from_value(params)
+                .map_err(|_| (INVALID_PARAMS, "Invalid params".to_owned()))?;
+            match user_db.get(id) {
+                Some(user) => to_value(&UserWithId::new(id, user)),
+                None => Err((USER_NOT_FOUND, "User not found".to_owned())),
+            }
+        }
+        "user.list" => {
+            let list = user_db
+                .list()
+                .into_iter()
+                .map(|(id, user)| UserWithId::new(id, user))
+                .collect::<Vec<_>>();
+            to_value(&list)
+        }
+        "user.delete" => {
+            let IdParams { id } = serde_json::from_value(params)
+                .map_err(|_| (INVALID_PARAMS, "Invalid params".to_owned()))?;
+            if user_db.remove(id) {
+                Ok(Value::Bool(true))
+            } else {
+                Err((USER_NOT_FOUND, "User not found".to_owned()))
+            }
+        }
+        _ => Err((METHOD_NOT_FOUND, "Method not found".to_owned())),
+    }
+}
+
+fn to_value<T: Serialize>(value: &T) -> MethodResult {
+    serde_json::to_value(value).map_err(|_| (INTERNAL_ERROR, "Internal error".to_owned()))
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> RpcResponse {
+    RpcResponse {
+        jsonrpc: "2.0",
+        result: None,
+        error: Some(RpcError {
+            code,
+            message: message.to_owned(),
+        }),
+        id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryUserStore;
+    use futures::{Future, Stream};
+    use std::sync::Arc;
+
+    fn test_db() -> UserDb {
+        Arc::new(MemoryUserStore::new())
+    }
+
+    // `handle` buffers its response body into a plain `Body::from(String)`, so `.wait()`
+    // resolves it synchronously without needing a runtime.
+    fn response_json(response: Response<Body>) -> Value {
+        let bytes = response.into_body().concat2().wait().unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn batch_request_skips_notifications_and_preserves_order() {
+        let db = test_db();
+        let body = Chunk::from(
+            r#"[
+                {"jsonrpc":"2.0","method":"user.list","id":1},
+                {"jsonrpc":"2.0","method":"user.list"},
+                {"jsonrpc":"2.0","method":"user.list","id":2}
+            ]"#,
+        );
+
+        let value = response_json(handle(&body, &db));
+        let responses = value.as_array().expect("batch response should be a JSON array");
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[1]["id"], 2);
+    }
+
+    #[test]
+    fn a_single_notification_yields_no_content() {
+        let db = test_db();
+        let body = Chunk::from(r#"{"jsonrpc":"2.0","method":"user.list"}"#);
+
+        let response = handle(&body, &db);
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn a_batch_of_only_notifications_yields_no_content() {
+        let db = test_db();
+        let body = Chunk::from(
+            r#"[
+                {"jsonrpc":"2.0","method":"user.list"},
+                {"jsonrpc":"2.0","method":"user.list"}
+            ]"#,
+        );
+
+        let response = handle(&body, &db);
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn an_empty_batch_is_invalid_request() {
+        let db = test_db();
+        let body = Chunk::from(r#"[]"#);
+
+        let value = response_json(handle(&body, &db));
+        assert_eq!(value["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[test]
+    fn user_create_then_get_round_trips_through_call_method() {
+        let db = test_db();
+        let created = call_method(
+            "user.create",
+            serde_json::json!({"name": "alice", "email": "alice@example.com"}),
+            &db,
+        )
+        .expect("create should succeed");
+        let id = created["id"].as_u64().expect("created user should carry an id");
+
+        let fetched = call_method("user.get", serde_json::json!({"id": id}), &db)
+            .expect("get should succeed");
+        assert_eq!(fetched["name"], "alice");
+    }
+
+    #[test]
+    fn unknown_method_is_method_not_found() {
+        let db = test_db();
+        let err = call_method("does.not.exist", Value::Null, &db).unwrap_err();
+        assert_eq!(err.0, METHOD_NOT_FOUND);
+    }
+}
@@ -0,0 +1,273 @@
+// Storage backend abstraction for user records. Request-handling code (the router
+// handlers, the JSON-RPC dispatcher) only ever talks to a `&dyn UserStore`, so swapping
+// the in-memory `Slab` for the durable LMDB-backed store is a matter of which one gets
+// constructed in `main`, not a change to any route.
+use lmdb::{Cursor, Environment, Transaction, WriteFlags};
+use slab::Slab;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::{UserData, UserId};
+
+// Surfaced by `insert`/`update` when a backend can't accept the write right now (e.g.
+// LMDB's map filling up). Callers turn this into a `500` rather than letting the write
+// panic the request thread.
+#[derive(Debug)]
+pub enum StoreError {
+    Full,
+}
+
+pub trait UserStore: Send + Sync {
+    fn insert(&self, user: UserData) -> Result<UserId, StoreError>;
+    fn get(&self, id: UserId) -> Option<UserData>;
+    fn update(&self, id: UserId, user: UserData) -> Result<bool, StoreError>;
+    fn remove(&self, id: UserId) -> bool;
+    fn list(&self) -> Vec<(UserId, UserData)>;
+}
+
+// The original backend: users live only as long as the process does, allocated through a
+// `Slab` so ids can be reused once freed. `Slab` indexes with `usize`, so this is also
+// where the external `UserId` (`u64`, used on the wire and in LMDB keys) gets converted
+// to and from the allocator's own index type.
+pub struct MemoryUserStore {
+    users: Mutex<Slab<UserData>>,
+}
+
+impl MemoryUserStore {
+    pub fn new() -> Self {
+        MemoryUserStore {
+            users: Mutex::new(Slab::new()),
+        }
+    }
+}
+
+impl UserStore for MemoryUserStore {
+    fn insert(&self, user: UserData) -> Result<UserId, StoreError> {
+        Ok(self.users.lock().unwrap().insert(user) as UserId)
+    }
+
+    fn get(&self, id: UserId) -> Option<UserData> {
+        self.users.lock().unwrap().get(id as usize).cloned()
+    }
+
+    fn update(&self, id: UserId, user: UserData) -> Result<bool, StoreError> {
+        let mut users = self.users.lock().unwrap();
+        match users.get_mut(id as usize) {
+            Some(slot) => {
+                *slot = user;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn remove(&self, id: UserId) -> bool {
+        let mut users = self.users.lock().unwrap();
+        if users.contains(id as usize) {
+            users.remove(id as usize);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn list(&self) -> Vec<(UserId, UserData)> {
+        self.users
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, user)| (id as UserId, user.clone()))
+            .collect()
+    }
+}
+
+// A durable backend keyed by the numeric `UserId`, backed by an embedded LMDB database so
+// user records survive process restarts. `UserData` is serialized to the same JSON bytes
+// it already uses on the wire, rather than introducing a second encoding just for storage.
+pub struct LmdbUserStore {
+    env: Environment,
+    db: lmdb::Database,
+}
+
+// LMDB's own default map size is a conservative 10MiB, far too small for any real
+// workload; give the store real headroom up front instead of having every write past
+// that point fail with `MDB_MAP_FULL`.
+const MAP_SIZE: usize = 1024 * 1024 * 1024;
+
+impl LmdbUserStore {
+    pub fn open(path: &Path) -> lmdb::Result<Self> {
+        std::fs::create_dir_all(path).map_err(|_| lmdb::Error::Invalid)?;
+        let env = Environment::new().set_map_size(MAP_SIZE).open(path)?;
+        let db = env.open_db(None)?;
+        Ok(LmdbUserStore { env, db })
+    }
+
+    // LMDB has no auto-increment counter of its own, so the next id is just one past
+    // whatever the highest existing key is. `O(n)` per insert is fine at the scale this
+    // service runs at; a dedicated counter key is the place to go if that stops being true.
+    //
+    // This must run inside the same read-write transaction as the `put` that follows it:
+    // LMDB only ever allows one writer at a time, so reading the cursor max from within
+    // `txn` is already serialized against every other insert. Computing it from a separate
+    // transaction (as an earlier version of this did) let two concurrent inserts read the
+    // same max and silently overwrite each other.
+    fn next_id(txn: &lmdb::RwTransaction, db: lmdb::Database) -> lmdb::Result<UserId> {
+        let mut cursor = txn.open_ro_cursor(db)?;
+        let max_id = cursor.iter().map(|(key, _)| decode_id(key)).max();
+        Ok(max_id.map_or(0, |id| id + 1))
+    }
+}
+
+impl UserStore for LmdbUserStore {
+    fn insert(&self, user: UserData) -> Result<UserId, StoreError> {
+        let bytes = serde_json::to_vec(&user).expect("UserData always serializes");
+        let mut txn = self.env.begin_rw_txn().expect("lmdb: failed to start txn");
+        let id = Self::next_id(&txn, self.db).expect("lmdb: failed to allocate next id");
+        match txn.put(self.db, &encode_id(id), &bytes, WriteFlags::empty()) {
+            Ok(()) => {}
+            Err(lmdb::Error::MapFull) => return Err(StoreError::Full),
+            Err(e) => panic!("lmdb: failed to put record: {}", e),
+        }
+        txn.commit().expect("lmdb: failed to commit txn");
+        Ok(id)
+    }
+
+    fn get(&self, id: UserId) -> Option<UserData> {
+        let txn = self.env.begin_ro_txn().expect("lmdb: failed to start txn");
+        match txn.get(self.db, &encode_id(id)) {
+            Ok(bytes) => serde_json::from_slice(bytes).ok(),
+            Err(lmdb::Error::NotFound) => None,
+            Err(e) => panic!("lmdb: failed to read record: {}", e),
+        }
+    }
+
+    // The existence check and the write both run inside the same read-write transaction,
+    // for the same reason `insert`'s id allocation does: LMDB serializes writers, so doing
+    // the check-then-act split across two transactions (as an earlier version of this did)
+    // let a concurrent `remove` land in between and get silently resurrected by this `put`.
+    fn update(&self, id: UserId, user: UserData) -> Result<bool, StoreError> {
+        let bytes = serde_json::to_vec(&user).expect("UserData always serializes");
+        let mut txn = self.env.begin_rw_txn().expect("lmdb: failed to start txn");
+        match txn.get(self.db, &encode_id(id)) {
+            Ok(_) => {}
+            Err(lmdb::Error::NotFound) => return Ok(false),
+            Err(e) => panic!("lmdb: failed to read record: {}", e),
+        }
+        match txn.put(self.db, &encode_id(id), &bytes, WriteFlags::empty()) {
+            Ok(()) => {}
+            Err(lmdb::Error::MapFull) => return Err(StoreError::Full),
+            Err(e) => panic!("lmdb: failed to put record: {}", e),
+        }
+        txn.commit().expect("lmdb: failed to commit txn");
+        Ok(true)
+    }
+
+    fn remove(&self, id: UserId) -> bool {
+        let mut txn = self.env.begin_rw_txn().expect("lmdb: failed to start txn");
+        match txn.del(self.db, &encode_id(id), None) {
+            Ok(()) => {
+                txn.commit().expect("lmdb: failed to commit txn");
+                true
+            }
+            Err(lmdb::Error::NotFound) => false,
+            Err(e) => panic!("lmdb: failed to delete record: {}", e),
+        }
+    }
+
+    fn list(&self) -> Vec<(UserId, UserData)> {
+        let txn = self.env.begin_ro_txn().expect("lmdb: failed to start txn");
+        let mut cursor = txn
+            .open_ro_cursor(self.db)
+            .expect("lmdb: failed to open cursor");
+        cursor
+            .iter()
+            .map(|(key, bytes)| {
+                let user = serde_json::from_slice(bytes).expect("stored UserData is valid JSON");
+                (decode_id(key), user)
+            })
+            .collect()
+    }
+}
+
+fn encode_id(id: UserId) -> [u8; 8] {
+    id.to_be_bytes()
+}
+
+fn decode_id(bytes: &[u8]) -> UserId {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    UserId::from_be_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // Each test gets its own LMDB environment directory under the system temp dir, so
+    // tests running concurrently in the same process never contend for the same map.
+    fn temp_store() -> LmdbUserStore {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!(
+            "hyper-microservice-store-test-{}-{}",
+            std::process::id(),
+            n
+        ));
+        LmdbUserStore::open(&dir).expect("failed to open test LMDB store")
+    }
+
+    #[test]
+    fn insert_allocates_increasing_ids_and_round_trips_the_record() {
+        let store = temp_store();
+        let alice = store
+            .insert(UserData::new("alice", "alice@example.com", 1))
+            .expect("insert should succeed");
+        let bob = store
+            .insert(UserData::new("bob", "bob@example.com", 2))
+            .expect("insert should succeed");
+        assert_eq!(bob, alice + 1);
+
+        let fetched = store.get(alice).expect("inserted record should be readable");
+        assert_eq!(fetched.name, "alice");
+        assert_eq!(fetched.email, "alice@example.com");
+    }
+
+    // `next_id` is computed as one past the highest remaining key, not a monotonic
+    // counter, so removing the highest id frees it up to be handed out again.
+    #[test]
+    fn next_id_reuses_the_id_just_freed_from_the_highest_key() {
+        let store = temp_store();
+        let alice = store
+            .insert(UserData::new("alice", "alice@example.com", 1))
+            .expect("insert should succeed");
+        let bob = store
+            .insert(UserData::new("bob", "bob@example.com", 2))
+            .expect("insert should succeed");
+        assert_eq!(bob, alice + 1);
+
+        assert!(store.remove(bob));
+        let carol = store
+            .insert(UserData::new("carol", "carol@example.com", 3))
+            .expect("insert should succeed");
+        assert_eq!(carol, bob);
+    }
+
+    #[test]
+    fn update_reports_whether_the_record_exists() {
+        let store = temp_store();
+        let alice = store
+            .insert(UserData::new("alice", "alice@example.com", 1))
+            .expect("insert should succeed");
+
+        assert!(store
+            .update(alice, UserData::new("alice", "alice2@example.com", 1))
+            .expect("update should succeed"));
+        assert_eq!(store.get(alice).unwrap().email, "alice2@example.com");
+
+        assert!(store.remove(alice));
+        assert!(!store
+            .update(alice, UserData::new("alice", "alice3@example.com", 1))
+            .expect("update of a missing record should still succeed as `Ok(false)`"));
+    }
+}